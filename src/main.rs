@@ -2,9 +2,76 @@ use bevy::{
     log::{Level, LogPlugin},
     prelude::*,
     render::camera::ScalingMode,
+    utils::HashMap,
     window::{PresentMode, WindowMode},
 };
+use bevy_ggrs::{
+    AddRollbackCommandExtension, GgrsApp, GgrsPlugin, GgrsSchedule, LocalInputs, LocalPlayers,
+    PlayerInputs, ReadInputs,
+};
 use bevy_rapier2d::{prelude::*, rapier::prelude::CollisionEventFlags};
+use bytemuck::{Pod, Zeroable};
+use ggrs::{Config, PlayerType, SessionBuilder, UdpNonBlockingSocket};
+use std::net::SocketAddr;
+
+const FPS: usize = 60;
+const INPUT_JUMP: u8 = 1 << 0;
+const INPUT_START: u8 = 1 << 1; // requests a (re)start of the match; only read in Menu/GameOver
+
+// one bitfield byte sent over the wire per player per frame; must be Pod/Zeroable for GGRS
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Pod, Zeroable)]
+struct BoxInput {
+    pub buttons: u8,
+}
+
+struct GgrsConfig;
+
+impl Config for GgrsConfig {
+    type Input = BoxInput;
+    type State = u8;
+    type Address = SocketAddr;
+}
+
+// seeded at session start so both peers draw identical pipe layouts; `Copy` so GGRS can
+// snapshot/restore it like any other rolled-back resource
+#[derive(Resource, Clone, Copy)]
+struct MatchRng(u64);
+
+impl MatchRng {
+    fn seed_from_u64(seed: u64) -> Self {
+        Self(seed | 1) // xorshift never recovers from a zero state
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 32) as u32
+    }
+
+    fn gen_range(&mut self, range: std::ops::Range<i32>) -> i32 {
+        let span = (range.end - range.start) as u32;
+        range.start + (self.next_u32() % span) as i32
+    }
+}
+
+#[derive(States, Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+enum AppState {
+    #[default]
+    Menu,
+    Playing,
+    GameOver,
+}
+
+#[derive(Resource, Clone, Copy, Default)]
+struct PrevStartInput(bool); // rolled-back edge-detection state for the synced "start" input bit
+
+// who won the last completed match (None on a tie); read by the GameOver prompt
+#[derive(Resource, Default)]
+struct MatchResult {
+    winner: Option<usize>,
+}
 
 #[derive(PartialEq)]
 enum PipeType {
@@ -19,17 +86,43 @@ struct Pipe {
 } // tag component, with some extra meta-data
 
 #[derive(Component)]
-struct Player; // tag component
+struct Player(pub usize); // tag component, holds the GGRS handle (0 or 1) this bird is controlled by
+
+#[derive(Component, Clone, Copy, Default)]
+struct PrevJumpInput(pub bool); // rolled-back edge-detection state for the jump input
+
+#[derive(Component)]
+struct GameplayEntity; // tag component, anything spawned for a Playing run and despawned on exit
+
+#[derive(Component)]
+struct MenuText; // tag component, the "press space" prompt shown in Menu/GameOver
+
+#[derive(Component, Default)]
+struct Tilt(f32); // current eased tilt angle (radians), lives on the bird's child sprite
 
 #[derive(Component)]
+struct Particle {
+    velocity: Vec2,
+    lifetime: Timer,
+} // a short-lived sprite spawned by a feather puff or a debris burst
+
+enum ParticleBurst {
+    Feather(Vec3),
+    Debris(Vec3),
+}
+
+#[derive(Resource, Default)]
+struct ParticleQueue(Vec<ParticleBurst>); // particle-burst requests queued by gameplay systems
+
+#[derive(Component, Clone, Copy)]
 struct Health {
     pub current: u8,
 } // state component, many entities could have it
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy)]
 struct Score {
     pub current: u8,
-} // state component, many entities could have it, for example in a multiplayer game
+} // state component, many entities could have it, for a multiplayer game
 
 const SCREEN_WIDTH: f32 = 400.0;
 const SCREEN_HEIGHT: f32 = 600.0;
@@ -63,25 +156,179 @@ fn main() {
                     ..default()
                 }),
         )
-        .add_plugins(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(50.0))
+        // stepped from `GgrsSchedule` instead of `Update` so both peers simulate physics in lockstep
+        .add_plugins(
+            RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(50.0).in_schedule(GgrsSchedule),
+        )
         .add_plugins(RapierDebugRenderPlugin::default())
-        .add_systems(Startup, setup)
+        .add_plugins(GgrsPlugin::<GgrsConfig>::default())
+        .set_rollback_schedule_fps(FPS)
+        .rollback_component_with_copy::<Transform>()
+        .rollback_component_with_copy::<Velocity>()
+        .rollback_component_with_copy::<Health>()
+        .rollback_component_with_copy::<Score>()
+        .rollback_component_with_copy::<PrevJumpInput>()
+        .rollback_resource_with_copy::<MatchRng>()
+        .rollback_resource_with_copy::<PrevStartInput>()
+        .init_resource::<SfxQueue>()
+        .init_resource::<ParticleQueue>()
+        .init_resource::<MatchResult>()
+        .add_state::<AppState>()
+        .add_systems(Startup, (setup, start_ggrs_session))
+        .add_systems(OnEnter(AppState::Playing), spawn_game)
+        .add_systems(OnExit(AppState::Playing), despawn_game)
+        .add_systems(OnEnter(AppState::Menu), spawn_menu_text)
+        .add_systems(OnExit(AppState::Menu), despawn_menu_text)
+        .add_systems(OnEnter(AppState::GameOver), spawn_menu_text)
+        .add_systems(OnExit(AppState::GameOver), despawn_menu_text)
+        .add_systems(ReadInputs, read_local_inputs)
+        // `GgrsSchedule` is our fixed 1/60s simulation step (see `set_rollback_schedule_fps`
+        // above) — every physics-affecting system lives here, decoupled from render framerate,
+        // so `PIPE_SPEED`/`JUMP_FORCE` behave identically regardless of how fast the window runs.
         .add_systems(
-            Update,
+            GgrsSchedule,
             (
                 process_player_input,
+                process_pipes,
                 handle_collision_events,
                 check_player_health,
-                process_pipes,
-                update_score,
-            ),
+            )
+                .chain()
+                .run_if(in_state(AppState::Playing)),
+        )
+        // stepped from `GgrsSchedule`, not `Update`, so both peers read the synced start bit
+        // on the same simulated frame (see `handle_menu_input`)
+        .add_systems(
+            GgrsSchedule,
+            handle_menu_input
+                .run_if(in_state(AppState::Menu).or_else(in_state(AppState::GameOver))),
+        )
+        .add_systems(
+            Update,
+            (update_score, apply_tilt_tween).run_if(in_state(AppState::Playing)),
         )
+        // drained unconditionally: state transitions apply before `Update` runs, so a drain
+        // gated on `in_state(Playing)` would miss a fatal hit queued on the same tick; the
+        // particles it spawns are then ticked and culled in GameOver too
+        .add_systems(Update, (play_queued_sfx, spawn_particle_bursts, update_particles))
         .add_event::<CollisionEvent>()
         .run();
 }
 
+// ReadInputs: GGRS calls this every rollback step to collect this peer's local input.
+fn read_local_inputs(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    local_players: Res<LocalPlayers>,
+) {
+    let mut local_inputs = HashMap::new();
+
+    for handle in &local_players.0 {
+        let mut buttons = 0u8;
+        if keyboard_input.pressed(KeyCode::Space) {
+            // same key serves both roles; `handle_menu_input`/`process_player_input` each only
+            // look at the bit they care about, gated by which `AppState` they run in
+            buttons |= INPUT_JUMP;
+            buttons |= INPUT_START;
+        }
+        local_inputs.insert(*handle, BoxInput { buttons });
+    }
+
+    commands.insert_resource(LocalInputs::<GgrsConfig>(local_inputs));
+}
+
+// Startup: opens the GGRS P2P session. Both peers must pass the same `--seed` for `MatchRng`
+// to produce identical pipe layouts; ports/addresses are read from argv instead of hardcoded.
+fn start_ggrs_session(mut commands: Commands) {
+    let args: Vec<String> = std::env::args().collect();
+    let local_port: u16 = arg_value(&args, "--local-port")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(7000);
+    let remote_addr: SocketAddr = arg_value(&args, "--remote-addr")
+        .unwrap_or_else(|| "127.0.0.1:7001".into())
+        .parse()
+        .expect("invalid --remote-addr");
+    // the handle (0 or 1) this process owns; peers must start with opposite values
+    let player_index: usize = arg_value(&args, "--player-index")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    let seed: u64 = arg_value(&args, "--seed")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+
+    let mut session_builder = SessionBuilder::<GgrsConfig>::new()
+        .with_num_players(2)
+        .with_input_delay(2);
+
+    for handle in 0..2 {
+        let player_type = if handle == player_index {
+            PlayerType::Local
+        } else {
+            PlayerType::Remote(remote_addr)
+        };
+        session_builder = session_builder
+            .add_player(player_type, handle)
+            .expect("failed to add player");
+    }
+
+    let session = session_builder
+        .start_p2p_session(
+            UdpNonBlockingSocket::bind_to_port(local_port).expect("failed to bind udp socket"),
+        )
+        .expect("failed to start GGRS session");
+
+    commands.insert_resource(bevy_ggrs::Session::P2P(session));
+    commands.insert_resource(MatchRng::seed_from_u64(seed));
+}
+
+fn arg_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+// the frame number GGRS is currently simulating; used by `LastEventFrame` to tell a
+// resimulation of an old frame from a genuinely new one
+fn current_frame(session: &bevy_ggrs::Session<GgrsConfig>) -> i32 {
+    match session {
+        bevy_ggrs::Session::P2P(session) => session.current_frame(),
+        _ => 0,
+    }
+}
+
+#[derive(Resource)]
+struct GameAssets {
+    bird_sprite: Handle<Image>,
+    pipe_sprite: Handle<Image>,
+    particle_sprite: Handle<Image>,
+    font: Handle<Font>,
+    flap_sfx: Handle<AudioSource>,
+    score_sfx: Handle<AudioSource>,
+    hit_sfx: Handle<AudioSource>,
+}
+
+// a sound-effect request; gameplay systems queue these instead of touching the audio API
+enum Sfx {
+    Flap,
+    Score,
+    Hit,
+}
+
+#[derive(Resource, Default)]
+struct SfxQueue(Vec<Sfx>);
+
+// the simulated frame each event kind was last queued on, one per player. Deliberately not
+// rollback-registered so it survives a misprediction replay and dedupes the one-shot queue pushes.
+#[derive(Component, Default)]
+struct LastEventFrame {
+    flap: Option<i32>,
+    score: Option<i32>,
+    hit: Option<i32>,
+}
+
 fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let default_sprite = asset_server.load("sprites/yellowbird-midflap.png");
+    let bird_sprite: Handle<Image> = asset_server.load("sprites/yellowbird-midflap.png");
 
     let pipe_sprite: Handle<Image> = asset_server.load("sprites/pipe-green.png");
 
@@ -89,6 +336,26 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
 
     let base: Handle<Image> = asset_server.load("sprites/base.png");
 
+    let font: Handle<Font> = asset_server.load("fonts/flappy-font.ttf");
+
+    let flap_sfx: Handle<AudioSource> = asset_server.load("audio/flap.ogg");
+
+    let score_sfx: Handle<AudioSource> = asset_server.load("audio/score.ogg");
+
+    let hit_sfx: Handle<AudioSource> = asset_server.load("audio/hit.ogg");
+
+    let particle_sprite: Handle<Image> = asset_server.load("sprites/particle.png");
+
+    commands.insert_resource(GameAssets {
+        bird_sprite,
+        pipe_sprite,
+        particle_sprite,
+        font: font.clone(),
+        flap_sfx,
+        score_sfx,
+        hit_sfx,
+    });
+
     commands.spawn(Camera2dBundle {
         projection: OrthographicProjection {
             scaling_mode: ScalingMode::AutoMax {
@@ -127,28 +394,79 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
             ..default()
         });
 
-    commands
-        .spawn(RigidBody::Dynamic)
-        .insert(Damping {
-            angular_damping: 0.0,
-            linear_damping: 0.0,
-            ..default()
-        })
-        .insert(GravityScale(8.0))
-        .insert(Collider::ball(12.0))
-        .insert(ActiveEvents::COLLISION_EVENTS)
-        .insert(Velocity {
-            linvel: Vec2::new(0.0, 0.0),
-            angvel: 0.0,
-        })
-        .insert(Player)
-        .insert(Health { current: 1 })
-        .insert(Score { current: 0 })
-        .insert(SpriteBundle {
-            texture: default_sprite,
-            transform: Transform::from_xyz(0.0, 0.0, 2.0),
+    commands.spawn((
+        // Create a TextBundle that has a Text with a single section.
+        TextBundle::from_section(
+            // Accepts a `String` or any type that converts into a `String`, such as `&str`
+            "0",
+            TextStyle {
+                // This font is loaded and will be used instead of the default font.
+                font,
+                font_size: 100.0,
+                color: Color::WHITE,
+            },
+        ) // Set the alignment of the Text
+        .with_text_alignment(TextAlignment::Center)
+        // Set the style of the TextBundle itself.
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(5.0),
+            right: Val::Px(5.0),
             ..default()
-        });
+        }),
+        TextScore,
+    ));
+}
+
+// OnEnter(AppState::Playing): spawns both players' birds and a fresh pipe layout for the run.
+fn spawn_game(
+    mut commands: Commands,
+    assets: Res<GameAssets>,
+    mut rng: ResMut<MatchRng>,
+    mut match_result: ResMut<MatchResult>,
+) {
+    match_result.winner = None;
+
+    for handle in 0..2 {
+        commands
+            .spawn(RigidBody::Dynamic)
+            .insert(Damping {
+                angular_damping: 0.0,
+                linear_damping: 0.0,
+                ..default()
+            })
+            .insert(GravityScale(8.0))
+            .insert(Collider::ball(12.0))
+            .insert(ActiveEvents::COLLISION_EVENTS)
+            .insert(Velocity {
+                linvel: Vec2::new(0.0, 0.0),
+                angvel: 0.0,
+            })
+            .insert(Player(handle))
+            .insert(PrevJumpInput::default())
+            .insert(LastEventFrame::default())
+            .insert(Health { current: 1 })
+            .insert(Score { current: 0 })
+            .insert(GameplayEntity)
+            .insert(TransformBundle::from_transform(Transform::from_xyz(
+                0.0,
+                handle as f32 * 40.0 - 20.0,
+                0.0,
+            )))
+            .add_rollback()
+            .with_children(|children| {
+                // the sprite lives on a child so its tween-eased tilt (cosmetic, not
+                // rolled back) never fights the physics body's rollback-synced Transform
+                children.spawn((
+                    SpriteBundle {
+                        texture: assets.bird_sprite.clone(),
+                        transform: Transform::from_xyz(0.0, 0.0, 2.0),
+                        ..default()
+                    },
+                    Tilt::default(),
+                ));
+            });
+    }
 
     let mut y_offset: f32 = 0.0;
 
@@ -156,7 +474,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
         if n % 2 == 0 {
             // lower pipes
 
-            y_offset = fastrand::i32((-SCREEN_HEIGHT as i32 / 2)..-180) as f32;
+            y_offset = rng.gen_range((-SCREEN_HEIGHT as i32 / 2)..-180) as f32;
 
             commands
                 .spawn(RigidBody::KinematicVelocityBased)
@@ -167,7 +485,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     linvel: Vec2::new(PIPE_SPEED, 0.0),
                 })
                 .insert(SpriteBundle {
-                    texture: pipe_sprite.clone(),
+                    texture: assets.pipe_sprite.clone(),
                     transform: Transform::from_xyz(
                         SCREEN_WIDTH / 2.0 + n as f32 * 70.0,
                         y_offset as f32,
@@ -178,7 +496,9 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                 .insert(Pipe {
                     pipe_type: PipeType::DOWN,
                     original_x: SCREEN_WIDTH / 2.0 + n as f32 * 70.0,
-                });
+                })
+                .insert(GameplayEntity)
+                .add_rollback();
         } else {
             // upper pipes
             commands
@@ -189,7 +509,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     linvel: Vec2::new(PIPE_SPEED, 0.0),
                 })
                 .insert(SpriteBundle {
-                    texture: pipe_sprite.clone(),
+                    texture: assets.pipe_sprite.clone(),
                     sprite: Sprite {
                         flip_y: true,
                         ..default()
@@ -207,6 +527,8 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                     pipe_type: PipeType::UP,
                     original_x: SCREEN_WIDTH / 2.0 + (n - 1) as f32 * 70.0,
                 })
+                .insert(GameplayEntity)
+                .add_rollback()
                 .with_children(|children| {
                     // will be used to detect when the player passes through the pipes
                     children
@@ -223,87 +545,238 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
                 });
         }
     }
+}
+
+// OnExit(AppState::Playing): clears the run so the next OnEnter starts from a clean slate.
+fn despawn_game(mut commands: Commands, query: Query<Entity, With<GameplayEntity>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// OnEnter(Menu)/OnEnter(GameOver): the "press space to (re)start" prompt.
+fn spawn_menu_text(
+    mut commands: Commands,
+    assets: Res<GameAssets>,
+    state: Res<State<AppState>>,
+    match_result: Res<MatchResult>,
+) {
+    let message = match state.get() {
+        AppState::Menu => "Press space to start".to_string(),
+        AppState::GameOver => match match_result.winner {
+            Some(handle) => format!("P{} wins! Press space to restart", handle + 1),
+            None => "Draw! Press space to restart".to_string(),
+        },
+        AppState::Playing => return,
+    };
 
     commands.spawn((
-        // Create a TextBundle that has a Text with a single section.
         TextBundle::from_section(
-            // Accepts a `String` or any type that converts into a `String`, such as `&str`
-            "0",
+            message,
             TextStyle {
-                // This font is loaded and will be used instead of the default font.
-                font: asset_server.load("fonts/flappy-font.ttf"),
-                font_size: 100.0,
+                font: assets.font.clone(),
+                font_size: 32.0,
                 color: Color::WHITE,
             },
-        ) // Set the alignment of the Text
+        )
         .with_text_alignment(TextAlignment::Center)
-        // Set the style of the TextBundle itself.
         .with_style(Style {
             position_type: PositionType::Absolute,
-            bottom: Val::Px(5.0),
-            right: Val::Px(5.0),
+            top: Val::Px(250.0),
+            left: Val::Px(0.0),
+            right: Val::Px(0.0),
             ..default()
         }),
-        TextScore,
+        MenuText,
     ));
 }
 
+// OnExit(Menu)/OnExit(GameOver): removes the prompt before gameplay/the next screen takes over.
+fn despawn_menu_text(mut commands: Commands, query: Query<Entity, With<MenuText>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+// GgrsSchedule, Menu/GameOver: gates the Playing transition on the synced `start` bit rather
+// than a raw local keypress, so both peers leave Menu/GameOver on the same simulated frame.
+// Known gap: `next_state.set` isn't itself rollback-safe (see `check_player_health`).
+fn handle_menu_input(
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    mut prev_start: ResMut<PrevStartInput>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    let start_pressed = (0..2).any(|handle| inputs[handle].0.buttons & INPUT_START != 0);
+    let start_just_pressed = start_pressed && !prev_start.0;
+    prev_start.0 = start_pressed;
+
+    if start_just_pressed {
+        next_state.set(AppState::Playing);
+    }
+}
+
 fn update_score(
-    mut text: Query<(&mut Text, With<TextScore>)>,
-    score: Query<(&Score, With<Score>, With<Player>)>,
+    mut text: Query<&mut Text, With<TextScore>>,
+    mut players: Query<(&Score, &Player)>,
 ) {
-    let mut score_text = text.single_mut();
+    let mut scores: Vec<(usize, u8)> = players
+        .iter_mut()
+        .map(|(score, player)| (player.0, score.current))
+        .collect();
+    scores.sort_by_key(|(handle, _)| *handle);
+
+    text.single_mut().sections[0].value = scores
+        .iter()
+        .map(|(handle, current)| format!("P{}: {current}", handle + 1))
+        .collect::<Vec<_>>()
+        .join("  ");
+}
 
-    score_text.0.sections[0].value = score.single().0.current.to_string();
+// drains the sound-effect intents queued by gameplay systems and fires their playback
+fn play_queued_sfx(mut commands: Commands, assets: Res<GameAssets>, mut sfx: ResMut<SfxQueue>) {
+    for request in sfx.0.drain(..) {
+        let source = match request {
+            Sfx::Flap => assets.flap_sfx.clone(),
+            Sfx::Score => assets.score_sfx.clone(),
+            Sfx::Hit => assets.hit_sfx.clone(),
+        };
+
+        commands.spawn(AudioBundle {
+            source,
+            settings: PlaybackSettings::DESPAWN,
+            ..default()
+        });
+    }
 }
 
-fn process_player_input(
-    input: Res<Input<KeyCode>>,
-    mut _player: Query<(
-        &mut Velocity,
-        &mut Transform,
-        &mut Health,
-        With<Player>,
-        Without<Pipe>,
-    )>,
-    mut pipes: Query<(&mut Transform, &Pipe, With<Pipe>, Without<Player>)>,
-    mut rapier_config: ResMut<RapierConfiguration>, // we access the rapier config to resume the physics pipeline
+const TILT_EASE_UP: f32 = 14.0; // ease-out rate while climbing from a flap
+const TILT_EASE_DOWN: f32 = 5.0; // slower ease-in rate while diving
+const TILT_MAX: f32 = 0.7; // radians, matches the old linear map's rough range
+
+// eases the bird sprite's tilt toward a target angle instead of snapping to it; runs on the
+// child sprite so it never touches the rollback-synced Transform on the physics body
+fn apply_tilt_tween(
+    time: Res<Time>,
+    players: Query<&Velocity, With<Player>>,
+    mut tilted: Query<(&mut Transform, &mut Tilt, &Parent)>,
 ) {
-    let mut player = _player.single_mut();
+    for (mut transform, mut tilt, parent) in &mut tilted {
+        let Ok(velocity) = players.get(parent.get()) else {
+            continue;
+        };
+
+        let target = (velocity.linvel.y / JUMP_FORCE * 0.5).clamp(-TILT_MAX, TILT_MAX);
+        let ease_rate = if velocity.linvel.y > 0.0 {
+            TILT_EASE_UP
+        } else {
+            TILT_EASE_DOWN
+        };
 
-    if input.just_pressed(KeyCode::Space)
-        && player.1.translation.y < SCREEN_HEIGHT / 2.0
-        && player.2.current > 0
-    {
-        player.0.linvel = Vec2::new(0.0, JUMP_FORCE);
+        tilt.0 += (target - tilt.0) * (ease_rate * time.delta_seconds()).min(1.0);
+        transform.rotation = Quat::from_rotation_z(tilt.0);
     }
+}
 
-    // would be best to use a Variable Curve, or a Tween here
-    // https://bevyengine.org/examples/Animation/animated-transform/
-    player.1.rotation = Quat::from_rotation_z(player.0.linvel.y / JUMP_FORCE * 0.5);
+const PARTICLES_PER_BURST: usize = 8;
+const PARTICLE_LIFETIME_SECS: f32 = 0.4;
 
-    if input.just_pressed(KeyCode::R) {
-        info!("Restarting game");
+// drains the particle-burst intents queued by gameplay systems and spawns their particles
+fn spawn_particle_bursts(
+    mut commands: Commands,
+    assets: Res<GameAssets>,
+    mut bursts: ResMut<ParticleQueue>,
+) {
+    for burst in bursts.0.drain(..) {
+        let (origin, color, speed) = match burst {
+            ParticleBurst::Feather(origin) => (origin, Color::rgba(1.0, 0.95, 0.6, 0.9), 60.0),
+            ParticleBurst::Debris(origin) => (origin, Color::rgba(0.6, 0.3, 0.2, 0.9), 140.0),
+        };
+
+        for _ in 0..PARTICLES_PER_BURST {
+            let angle = fastrand::f32() * std::f32::consts::TAU;
+            let velocity = Vec2::new(angle.cos(), angle.sin()) * speed;
+
+            commands.spawn((
+                SpriteBundle {
+                    texture: assets.particle_sprite.clone(),
+                    sprite: Sprite {
+                        color,
+                        custom_size: Some(Vec2::splat(4.0)),
+                        ..default()
+                    },
+                    transform: Transform::from_translation(origin),
+                    ..default()
+                },
+                Particle {
+                    velocity,
+                    lifetime: Timer::from_seconds(PARTICLE_LIFETIME_SECS, TimerMode::Once),
+                },
+            ));
+        }
+    }
+}
 
-        for (mut pipe_transform, pipe, _, _) in pipes.iter_mut() {
-            pipe_transform.translation = Vec3::new(
-                pipe.original_x,
-                pipe_transform.translation.y,
-                pipe_transform.translation.z,
-            );
+// advances each particle's position/lifetime and fades it out, culling it on expiry
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut particles: Query<(Entity, &mut Transform, &mut Sprite, &mut Particle)>,
+) {
+    for (entity, mut transform, mut sprite, mut particle) in &mut particles {
+        particle.lifetime.tick(time.delta());
+
+        if particle.lifetime.finished() {
+            commands.entity(entity).despawn();
+            continue;
         }
 
-        player.0.linvel = Vec2::new(0.0, 0.0);
-        player.0.angvel = 0.0;
-        player.2.current = 1;
-        player.1.translation = Vec3::new(0.0, 0.0, 0.0);
-        player.1.rotation = Quat::from_rotation_z(0.0);
+        transform.translation += (particle.velocity * time.delta_seconds()).extend(0.0);
+        sprite.color.set_a(particle.lifetime.percent_left());
+    }
+}
+
+fn process_player_input(
+    session: Res<bevy_ggrs::Session<GgrsConfig>>,
+    inputs: Res<PlayerInputs<GgrsConfig>>,
+    mut players: Query<
+        (
+            &mut Velocity,
+            &Transform,
+            &Health,
+            &Player,
+            &mut PrevJumpInput,
+            &mut LastEventFrame,
+        ),
+        Without<Pipe>,
+    >,
+    mut sfx: ResMut<SfxQueue>,
+    mut particles: ResMut<ParticleQueue>,
+) {
+    let frame = current_frame(&session);
+
+    for (mut velocity, transform, health, player, mut prev_jump, mut last_event) in &mut players {
+        let (input, _) = inputs[player.0];
+        let jump_pressed = input.buttons & INPUT_JUMP != 0;
+        let jump_just_pressed = jump_pressed && !prev_jump.0;
+        prev_jump.0 = jump_pressed;
 
-        rapier_config.physics_pipeline_active = true; // resume the physics pipeline
+        if jump_just_pressed && transform.translation.y < SCREEN_HEIGHT / 2.0 && health.current > 0
+        {
+            velocity.linvel = Vec2::new(0.0, JUMP_FORCE);
+
+            // a GGRS misprediction replays this same frame; only queue the sfx/burst once
+            if last_event.flap != Some(frame) {
+                sfx.0.push(Sfx::Flap);
+                particles
+                    .0
+                    .push(ParticleBurst::Feather(transform.translation));
+                last_event.flap = Some(frame);
+            }
+        }
     }
 }
 
-fn process_pipes(mut query: Query<(&mut Transform, &Pipe, With<Pipe>)>) {
+fn process_pipes(mut query: Query<(&mut Transform, &Pipe, With<Pipe>)>, mut rng: ResMut<MatchRng>) {
     let mut y_offset: f32 = 0.0;
 
     for (mut transform, pipe, _) in query.iter_mut() {
@@ -311,7 +784,7 @@ fn process_pipes(mut query: Query<(&mut Transform, &Pipe, With<Pipe>)>) {
         // 50 is the pipe's width
         {
             if pipe.pipe_type == PipeType::DOWN {
-                y_offset = fastrand::i32((-SCREEN_HEIGHT as i32 / 2)..-180) as f32;
+                y_offset = rng.gen_range((-SCREEN_HEIGHT as i32 / 2)..-180) as f32;
 
                 transform.translation.y = y_offset;
             } else {
@@ -327,40 +800,91 @@ fn process_pipes(mut query: Query<(&mut Transform, &Pipe, With<Pipe>)>) {
     }
 }
 
+// the survivor wins on a single KO; on a simultaneous double-KO the higher score wins, or it's
+// a draw on a tie. `by_handle` must be sorted and indexed by player handle (0, 1).
+fn resolve_winner(by_handle: &[(usize, u8, u8)]) -> Option<usize> {
+    match *by_handle {
+        [(h0, health0, score0), (h1, health1, score1)] => match (health0 == 0, health1 == 0) {
+            (true, false) => Some(h1),
+            (false, true) => Some(h0),
+            (true, true) => (score0 != score1).then(|| if score0 > score1 { h0 } else { h1 }),
+            (false, false) => None,
+        },
+        _ => None,
+    }
+}
+
+// feeds the GameOver prompt via `MatchResult`. Like `handle_menu_input`, `next_state.set` here
+// isn't rollback-safe — it runs from the predicted step, not a confirmed frame.
 fn check_player_health(
-    _player: Query<(&Health, Entity, With<Player>)>,
-    mut rapier_config: ResMut<RapierConfiguration>, // we access the rapier config to stop the physics pipeline
+    players: Query<(&Health, &Score, &Player)>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut match_result: ResMut<MatchResult>,
 ) {
-    let player = _player.single();
-
-    if player.0.current <= 0 {
-        info!("Player is dead");
-        rapier_config.physics_pipeline_active = false; // stop the physics pipeline
+    if !players.iter().any(|(health, _, _)| health.current == 0) {
+        return;
     }
+
+    info!("A player is dead");
+
+    let mut by_handle: Vec<_> = players
+        .iter()
+        .map(|(health, score, player)| (player.0, health.current, score.current))
+        .collect();
+    by_handle.sort_by_key(|(handle, _, _)| *handle);
+
+    match_result.winner = resolve_winner(&by_handle);
+
+    next_state.set(AppState::GameOver);
 }
 
 fn handle_collision_events(
+    session: Res<bevy_ggrs::Session<GgrsConfig>>,
     mut collision_events: EventReader<CollisionEvent>,
-    mut player: Query<(&mut Health, Entity, &mut Score, With<Player>)>,
+    mut players: Query<(
+        &mut Health,
+        Entity,
+        &mut Score,
+        &Transform,
+        &mut LastEventFrame,
+        With<Player>,
+    )>,
+    mut sfx: ResMut<SfxQueue>,
+    mut particles: ResMut<ParticleQueue>,
 ) {
+    let frame = current_frame(&session);
+
     for collision_event in collision_events.iter() {
         match collision_event {
             CollisionEvent::Started(e1, e2, flags) => {
-                let mut _player = player.single_mut();
-
-                // use the flags to check if it's collision or sensor
-                if flags.contains(CollisionEventFlags::SENSOR)
-                    && (e1.index() == _player.1.index() || e2.index() == _player.1.index())
-                {
-                    info!("Player passed through pipes");
-
-                    _player.2.current += 1;
-                    info!("Player score now is: {}", _player.2.current);
-                } else {
-                    if e1.index() == _player.1.index() || e2.index() == _player.1.index() {
+                for (mut health, entity, mut score, transform, mut last_event, _) in &mut players {
+                    if e1.index() != entity.index() && e2.index() != entity.index() {
+                        continue;
+                    }
+
+                    // use the flags to check if it's collision or sensor
+                    if flags.contains(CollisionEventFlags::SENSOR) {
+                        info!("Player passed through pipes");
+
+                        // `Score` is rollback-tracked so this recomputes identically on
+                        // resimulation; only the one-shot sfx push needs the dedup guard
+                        score.current += 1;
+                        info!("Player score now is: {}", score.current);
+                        if last_event.score != Some(frame) {
+                            sfx.0.push(Sfx::Score);
+                            last_event.score = Some(frame);
+                        }
+                    } else {
                         info!("Player collided with something");
-                        _player.0.current = 0;
-                        info!("Player health now is: {}", _player.0.current);
+                        health.current = 0;
+                        info!("Player health now is: {}", health.current);
+                        if last_event.hit != Some(frame) {
+                            sfx.0.push(Sfx::Hit);
+                            particles
+                                .0
+                                .push(ParticleBurst::Debris(transform.translation));
+                            last_event.hit = Some(frame);
+                        }
                     }
                 }
             }
@@ -368,3 +892,40 @@ fn handle_collision_events(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn match_rng_is_deterministic_for_a_given_seed() {
+        let mut a = MatchRng::seed_from_u64(42);
+        let mut b = MatchRng::seed_from_u64(42);
+
+        for _ in 0..100 {
+            assert_eq!(a.gen_range(-300..-180), b.gen_range(-300..-180));
+        }
+    }
+
+    #[test]
+    fn resolve_winner_single_ko() {
+        assert_eq!(resolve_winner(&[(0, 0, 3), (1, 1, 1)]), Some(1));
+        assert_eq!(resolve_winner(&[(0, 1, 1), (1, 0, 3)]), Some(0));
+    }
+
+    #[test]
+    fn resolve_winner_double_ko_breaks_tie_on_score() {
+        assert_eq!(resolve_winner(&[(0, 0, 1), (1, 0, 3)]), Some(1));
+        assert_eq!(resolve_winner(&[(0, 0, 3), (1, 0, 1)]), Some(0));
+    }
+
+    #[test]
+    fn resolve_winner_double_ko_with_equal_score_is_a_draw() {
+        assert_eq!(resolve_winner(&[(0, 0, 2), (1, 0, 2)]), None);
+    }
+
+    #[test]
+    fn resolve_winner_no_ko_is_not_yet_decided() {
+        assert_eq!(resolve_winner(&[(0, 1, 0), (1, 1, 0)]), None);
+    }
+}